@@ -1,7 +1,7 @@
 use std::fmt;
 
 /// CharCount holds a count of a single character
-#[derive(PartialEq, Eq, Hash)]
+#[derive(PartialEq, Eq, Hash, Clone)]
 pub struct CharCount {
     pub letter: char,
     pub count: usize,
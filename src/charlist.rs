@@ -1,37 +1,58 @@
 use crate::charcount::CharCount;
 use std::fmt;
 
+/// Number of lanes in the dense representation, one per lowercase ascii
+/// letter, rounded up so the array lines up nicely for SIMD-style lanewise
+/// comparisons.
+const DENSE_LANES: usize = 32;
+
 /// CharList stores a list of letters and their counts.
-/// The items in the list are guaranteed to be in order.
-#[derive(PartialEq, Eq, Hash, Debug)]
-pub struct CharList {
-    length: usize,
-    list: Vec<CharCount>,
+///
+/// Most candidate words only ever contain lowercase ascii letters, so those
+/// are stored as `Dense`: a fixed-width array of per-letter counts that makes
+/// `subtract`/`filter` branchless lanewise comparisons instead of a
+/// merge-walk. Anything containing a character outside that bounded alphabet
+/// (unicode letters, punctuation, digits, whitespace) falls back to `Sparse`,
+/// which keeps the original sorted `Vec<CharCount>` representation.
+/// `combine`, `subtract` and `filter` accept either representation on either
+/// side. Equality and hashing do not: the same letters stored as `Dense` and
+/// as `Sparse` are not equal.
+#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+pub enum CharList {
+    Dense {
+        length: usize,
+        lanes: [u8; DENSE_LANES],
+    },
+    Sparse {
+        length: usize,
+        list: Vec<CharCount>,
+    },
 }
 
 impl CharList {
-    /// create a new CharList
+    /// create a new (empty) CharList
     pub fn new() -> CharList {
-        CharList {
+        CharList::Sparse {
             length: 0,
             list: Vec::new(),
         }
     }
     /// initialize with an existing charCount
     pub fn init(count: CharCount) -> CharList {
-        let mut l = Vec::new();
         let length = count.count;
-        l.push(count);
-        CharList {
-            length: length,
-            list: l,
+        CharList::Sparse {
+            length,
+            list: vec![count],
         }
     }
-    /// combine two CharLists into one new list.
+    /// combine two CharLists into one new (sparse) list.
     pub fn combine(first: CharList, second: CharList) -> CharList {
+        let length = first.length() + second.length();
+        let first_list = first.sparse_list();
+        let second_list = second.sparse_list();
         let mut result = Vec::new();
-        let mut iter1 = first.list.iter();
-        let mut iter2 = second.list.iter();
+        let mut iter1 = first_list.iter();
+        let mut iter2 = second_list.iter();
         let mut item1 = iter1.next();
         let mut item2 = iter2.next();
         loop {
@@ -40,8 +61,8 @@ impl CharList {
                     match item2 {
                         None => {
                             // both iterators are done, so we can return
-                            return CharList {
-                                length: first.length + second.length,
+                            return CharList::Sparse {
+                                length,
                                 list: result,
                             };
                         }
@@ -93,7 +114,14 @@ impl CharList {
         }
     }
     /// create a CharList out of a String
+    ///
+    /// If every character is a lowercase ascii letter, this builds the fast
+    /// `Dense` lane representation directly in one pass. Otherwise it falls
+    /// back to the `Sparse` representation via the old char-by-char combine.
     pub fn from_string(s: &str) -> CharList {
+        if let Some((length, lanes)) = CharList::try_dense_lanes(s) {
+            return CharList::Dense { length, lanes };
+        }
         let mut acc = CharList::new();
         for c in s.chars() {
             let cl = CharList::init(CharCount::new(c));
@@ -102,11 +130,80 @@ impl CharList {
         acc
     }
 
+    /// lane index for a character in the bounded alphabet, or None if the
+    /// character falls outside it (forcing the Sparse fallback).
+    fn lane_index(c: char) -> Option<usize> {
+        if c.is_ascii_lowercase() {
+            Some((c as u8 - b'a') as usize)
+        } else {
+            None
+        }
+    }
+
+    fn try_dense_lanes(s: &str) -> Option<(usize, [u8; DENSE_LANES])> {
+        let mut lanes = [0u8; DENSE_LANES];
+        let mut length = 0;
+        for c in s.chars() {
+            let idx = CharList::lane_index(c)?;
+            lanes[idx] = lanes[idx].checked_add(1)?;
+            length += 1;
+        }
+        Some((length, lanes))
+    }
+
+    /// view of this CharList as a sorted `Vec<CharCount>`, regardless of
+    /// which representation it is actually stored in. Used by the merge-walk
+    /// fallbacks and by anything that needs to cross between Dense and
+    /// Sparse operands.
+    fn sparse_list(&self) -> Vec<CharCount> {
+        match self {
+            CharList::Sparse { list, .. } => list.clone(),
+            CharList::Dense { lanes, .. } => (0..DENSE_LANES)
+                .filter(|&i| lanes[i] > 0)
+                .map(|i| CharCount {
+                    letter: (b'a' + i as u8) as char,
+                    count: lanes[i] as usize,
+                })
+                .collect(),
+        }
+    }
+
     /// subtract two CharLists
     pub fn subtract(big: &CharList, small: &CharList) -> MatchResult {
+        if let (
+            CharList::Dense {
+                length: big_length,
+                lanes: big_lanes,
+            },
+            CharList::Dense {
+                length: small_length,
+                lanes: small_lanes,
+            },
+        ) = (big, small)
+        {
+            let mut remainder = [0u8; DENSE_LANES];
+            for i in 0..DENSE_LANES {
+                if small_lanes[i] > big_lanes[i] {
+                    return MatchResult::NoMatch;
+                }
+                remainder[i] = big_lanes[i] - small_lanes[i];
+            }
+            let remaining_length = big_length - small_length;
+            return if remaining_length == 0 {
+                MatchResult::FullMatch
+            } else {
+                MatchResult::PartialMatch(CharList::Dense {
+                    length: remaining_length,
+                    lanes: remainder,
+                })
+            };
+        }
+
+        let big_list = big.sparse_list();
+        let small_list = small.sparse_list();
         let mut result = Vec::new();
-        let mut bigiter = big.list.iter();
-        let mut smalliter = small.list.iter();
+        let mut bigiter = big_list.iter();
+        let mut smalliter = small_list.iter();
         let mut bigc = bigiter.next();
         let mut smallc = smalliter.next();
         loop {
@@ -118,8 +215,8 @@ impl CharList {
                             if result.len() == 0 {
                                 return MatchResult::FullMatch;
                             } else {
-                                return MatchResult::PartialMatch(CharList {
-                                    length: big.length - small.length,
+                                return MatchResult::PartialMatch(CharList::Sparse {
+                                    length: big.length() - small.length(),
                                     list: result,
                                 });
                             }
@@ -167,10 +264,134 @@ impl CharList {
             }
         }
     }
-    /// filter = like subtract, but a boolean result
+    /// subtract, but tolerant of `small` overshooting `big`: instead of
+    /// failing as soon as one letter is missing, the deficit is added to a
+    /// running slack total, and only once that total exceeds `max_slack`
+    /// does this fail with `NoMatch`. The returned remainder only ever holds
+    /// what `big` actually had left over; it never goes negative.
+    pub fn subtract_with_slack(big: &CharList, small: &CharList, max_slack: usize) -> SlackMatchResult {
+        if let (
+            CharList::Dense {
+                lanes: big_lanes, ..
+            },
+            CharList::Dense {
+                lanes: small_lanes, ..
+            },
+        ) = (big, small)
+        {
+            let mut remainder = [0u8; DENSE_LANES];
+            let mut slack_used: usize = 0;
+            for i in 0..DENSE_LANES {
+                if small_lanes[i] > big_lanes[i] {
+                    slack_used += (small_lanes[i] - big_lanes[i]) as usize;
+                    if slack_used > max_slack {
+                        return SlackMatchResult::NoMatch;
+                    }
+                } else {
+                    remainder[i] = big_lanes[i] - small_lanes[i];
+                }
+            }
+            let remaining_length: usize = remainder.iter().map(|&c| c as usize).sum();
+            return SlackMatchResult::Match {
+                remainder: CharList::Dense {
+                    length: remaining_length,
+                    lanes: remainder,
+                },
+                slack_used,
+            };
+        }
+
+        let big_list = big.sparse_list();
+        let small_list = small.sparse_list();
+        let mut result = Vec::new();
+        let mut slack_used: usize = 0;
+        let mut bigiter = big_list.iter();
+        let mut smalliter = small_list.iter();
+        let mut bigc = bigiter.next();
+        let mut smallc = smalliter.next();
+        loop {
+            match bigc {
+                None => match smallc {
+                    None => {
+                        let remaining_length: usize = result.iter().map(|cc: &CharCount| cc.count).sum();
+                        return SlackMatchResult::Match {
+                            remainder: CharList::Sparse {
+                                length: remaining_length,
+                                list: result,
+                            },
+                            slack_used,
+                        };
+                    }
+                    Some(cc2) => {
+                        // this letter of small doesn't appear in big at all
+                        slack_used += cc2.count;
+                        if slack_used > max_slack {
+                            return SlackMatchResult::NoMatch;
+                        }
+                        smallc = smalliter.next();
+                    }
+                },
+                Some(cc1) => match smallc {
+                    None => {
+                        result.push(CharCount {
+                            letter: cc1.letter,
+                            count: cc1.count,
+                        });
+                        bigc = bigiter.next();
+                    }
+                    Some(cc2) => {
+                        if cc1.letter < cc2.letter {
+                            result.push(CharCount {
+                                letter: cc1.letter,
+                                count: cc1.count,
+                            });
+                            bigc = bigiter.next();
+                        } else if cc1.letter > cc2.letter {
+                            // big has no letters this low, so small's earlier letter is missing entirely
+                            slack_used += cc2.count;
+                            if slack_used > max_slack {
+                                return SlackMatchResult::NoMatch;
+                            }
+                            smallc = smalliter.next();
+                        } else {
+                            if cc1.count < cc2.count {
+                                slack_used += cc2.count - cc1.count;
+                                if slack_used > max_slack {
+                                    return SlackMatchResult::NoMatch;
+                                }
+                                bigc = bigiter.next();
+                                smallc = smalliter.next();
+                            } else if cc1.count > cc2.count {
+                                result.push(CharCount {
+                                    letter: cc1.letter,
+                                    count: cc1.count - cc2.count,
+                                });
+                                bigc = bigiter.next();
+                                smallc = smalliter.next();
+                            } else {
+                                bigc = bigiter.next();
+                                smallc = smalliter.next();
+                            }
+                        }
+                    }
+                },
+            }
+        }
+    }
+
+    /// filter = like subtract, but a boolean result and no
+    /// remainder materialized
     pub fn filter(big: &CharList, small: &CharList) -> bool {
-        let mut bigiter = big.list.iter();
-        let mut smalliter = small.list.iter();
+        if let (CharList::Dense { lanes: big_lanes, .. }, CharList::Dense { lanes: small_lanes, .. }) =
+            (big, small)
+        {
+            return (0..DENSE_LANES).all(|i| small_lanes[i] <= big_lanes[i]);
+        }
+
+        let big_list = big.sparse_list();
+        let small_list = small.sparse_list();
+        let mut bigiter = big_list.iter();
+        let mut smalliter = small_list.iter();
         let mut bigc = bigiter.next();
         let mut smallc = smalliter.next();
         loop {
@@ -210,13 +431,16 @@ impl CharList {
     }
 
     pub fn length(&self) -> usize {
-        self.length
+        match self {
+            CharList::Dense { length, .. } => *length,
+            CharList::Sparse { length, .. } => *length,
+        }
     }
 }
 impl fmt::Display for CharList {
     /// Formatter for CharList
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({:?})", self.list)
+        write!(f, "({:?})", self.sparse_list())
     }
 }
 
@@ -226,6 +450,7 @@ mod tests {
     use super::CharCount;
     use super::CharList;
     use super::MatchResult;
+    use super::SlackMatchResult;
 
     #[test]
     fn combine_same() {
@@ -238,9 +463,10 @@ mod tests {
             count: 3,
         });
         let l3: CharList = CharList::combine(l1, l2);
-        assert!(l3.list.len() == 1);
-        assert!(l3.list.get(0).unwrap().letter == 'a');
-        assert!(l3.list.get(0).unwrap().count == 5);
+        let list = l3.sparse_list();
+        assert!(list.len() == 1);
+        assert!(list.get(0).unwrap().letter == 'a');
+        assert!(list.get(0).unwrap().count == 5);
     }
 
     #[test]
@@ -254,11 +480,12 @@ mod tests {
             count: 2,
         });
         let l3: CharList = CharList::combine(l1, l2);
-        assert!(l3.list.len() == 2);
-        assert!(l3.list.get(0).unwrap().letter == 'a');
-        assert!(l3.list.get(0).unwrap().count == 2);
-        assert!(l3.list.get(1).unwrap().letter == 'b');
-        assert!(l3.list.get(1).unwrap().count == 3);
+        let list = l3.sparse_list();
+        assert!(list.len() == 2);
+        assert!(list.get(0).unwrap().letter == 'a');
+        assert!(list.get(0).unwrap().count == 2);
+        assert!(list.get(1).unwrap().letter == 'b');
+        assert!(list.get(1).unwrap().count == 3);
     }
 
     #[test]
@@ -278,25 +505,40 @@ mod tests {
         });
         let l5: CharList = CharList::combine(l4, l3);
 
-        assert!(l5.list.len() == 3);
-        assert!(l5.list.get(0).unwrap().letter == 'a');
-        assert!(l5.list.get(0).unwrap().count == 2);
-        assert!(l5.list.get(1).unwrap().letter == 'b');
-        assert!(l5.list.get(1).unwrap().count == 1);
-        assert!(l5.list.get(2).unwrap().letter == 'c');
-        assert!(l5.list.get(2).unwrap().count == 3);
+        let list = l5.sparse_list();
+        assert!(list.len() == 3);
+        assert!(list.get(0).unwrap().letter == 'a');
+        assert!(list.get(0).unwrap().count == 2);
+        assert!(list.get(1).unwrap().letter == 'b');
+        assert!(list.get(1).unwrap().count == 1);
+        assert!(list.get(2).unwrap().letter == 'c');
+        assert!(list.get(2).unwrap().count == 3);
     }
 
     #[test]
     fn from_string() {
         let l: CharList = CharList::from_string("01102010221");
-        assert!(l.list.len() == 3);
-        assert!(l.list.get(0).unwrap().letter == '0');
-        assert!(l.list.get(0).unwrap().count == 4);
-        assert!(l.list.get(1).unwrap().letter == '1');
-        assert!(l.list.get(1).unwrap().count == 4);
-        assert!(l.list.get(2).unwrap().letter == '2');
-        assert!(l.list.get(2).unwrap().count == 3);
+        let list = l.sparse_list();
+        assert!(list.len() == 3);
+        assert!(list.get(0).unwrap().letter == '0');
+        assert!(list.get(0).unwrap().count == 4);
+        assert!(list.get(1).unwrap().letter == '1');
+        assert!(list.get(1).unwrap().count == 4);
+        assert!(list.get(2).unwrap().letter == '2');
+        assert!(list.get(2).unwrap().count == 3);
+    }
+
+    #[test]
+    fn from_string_bounded_alphabet_is_dense() {
+        let l: CharList = CharList::from_string("abcde");
+        assert!(matches!(l, CharList::Dense { .. }));
+        assert!(l.length() == 5);
+    }
+
+    #[test]
+    fn from_string_outside_alphabet_is_sparse() {
+        let l: CharList = CharList::from_string("it's");
+        assert!(matches!(l, CharList::Sparse { .. }));
     }
 
     #[test]
@@ -338,13 +580,74 @@ mod tests {
         let m: MatchResult = CharList::subtract(&b, &s);
         assert!(m == MatchResult::PartialMatch(CharList::from_string("f")));
     }
+
+    #[test]
+    fn subtract_across_representations() {
+        // the goal contains a space, forcing Sparse, while the candidate
+        // word is plain lowercase ascii and goes through the Dense path.
+        let b: CharList = CharList::from_string("bed sheet");
+        let s: CharList = CharList::from_string("the");
+        let m: MatchResult = CharList::subtract(&b, &s);
+        assert!(m != MatchResult::NoMatch);
+    }
+
     #[test]
-    fn filter() {
+    fn filter_true() {
         let b: CharList = CharList::from_string("abcdef");
         let s: CharList = CharList::from_string("ebcda");
         let m = CharList::filter(&b, &s);
         assert!(m);
     }
+
+    #[test]
+    fn filter_false() {
+        let b: CharList = CharList::from_string("abcdef");
+        let s: CharList = CharList::from_string("ebcdag");
+        let m = CharList::filter(&b, &s);
+        assert!(!m);
+    }
+
+    #[test]
+    fn subtract_with_slack_exact_still_works() {
+        let b: CharList = CharList::from_string("abcdef");
+        let s: CharList = CharList::from_string("ebcda");
+        let m = CharList::subtract_with_slack(&b, &s, 0);
+        match m {
+            SlackMatchResult::Match {
+                remainder,
+                slack_used,
+            } => {
+                assert!(slack_used == 0);
+                assert!(remainder.length() == 1);
+            }
+            SlackMatchResult::NoMatch => panic!("expected a match"),
+        }
+    }
+
+    #[test]
+    fn subtract_with_slack_allows_small_overshoot() {
+        let b: CharList = CharList::from_string("abcde");
+        let s: CharList = CharList::from_string("abcdez");
+        let m = CharList::subtract_with_slack(&b, &s, 1);
+        match m {
+            SlackMatchResult::Match {
+                remainder,
+                slack_used,
+            } => {
+                assert!(slack_used == 1);
+                assert!(remainder.length() == 0);
+            }
+            SlackMatchResult::NoMatch => panic!("expected a slack match"),
+        }
+    }
+
+    #[test]
+    fn subtract_with_slack_exceeding_budget_fails() {
+        let b: CharList = CharList::from_string("abcde");
+        let s: CharList = CharList::from_string("abcdezz");
+        let m = CharList::subtract_with_slack(&b, &s, 1);
+        assert!(m == SlackMatchResult::NoMatch);
+    }
 }
 
 /// Enum MatchResult holds the result of subtracting one charlist from another
@@ -354,3 +657,13 @@ pub enum MatchResult {
     FullMatch,
     PartialMatch(CharList),
 }
+
+/// Result of `CharList::subtract_with_slack`: either the deficit exceeded
+/// the slack budget (`NoMatch`), or it stayed within budget and `remainder`
+/// holds whatever `big` had left over (possibly empty) alongside how much
+/// slack was actually spent tolerating `small`'s overshoot.
+#[derive(PartialEq, Eq, Debug)]
+pub enum SlackMatchResult {
+    NoMatch,
+    Match { remainder: CharList, slack_used: usize },
+}
@@ -0,0 +1,67 @@
+//! Hash-target search support.
+//!
+//! In hash-target mode the tool does not score and print the best anagrams;
+//! instead it is given a set of digests (from `-H`) and reports only the
+//! anagram phrase(s) whose digest equals one of them. Hashing is
+//! order-sensitive while anagram multisets are not, so callers must expand
+//! every word-order permutation of a solution before hashing it.
+
+use md5;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+
+/// compute the md5 digest of a phrase as a lowercase hex string
+pub fn digest_hex_md5(phrase: &str) -> String {
+    format!("{:x}", md5::compute(phrase))
+}
+
+/// compute the sha256 digest of a phrase as a lowercase hex string
+pub fn digest_hex_sha256(phrase: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(phrase.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// if `phrase` matches one of `targets` (by md5 or sha256), return the
+/// matched hex digest so the caller can report which target was satisfied
+/// and stop looking for it.
+pub fn matching_target(phrase: &str, targets: &HashSet<String>) -> Option<String> {
+    let md5_hex = digest_hex_md5(phrase);
+    if targets.contains(&md5_hex) {
+        return Some(md5_hex);
+    }
+    let sha256_hex = digest_hex_sha256(phrase);
+    if targets.contains(&sha256_hex) {
+        return Some(sha256_hex);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn md5_matches_known_digest() {
+        let targets: HashSet<String> = vec!["900150983cd24fb0d6963f7d28e17f72".to_string()]
+            .into_iter()
+            .collect();
+        assert!(matching_target("abc", &targets).is_some());
+    }
+
+    #[test]
+    fn sha256_matches_known_digest() {
+        let targets: HashSet<String> = vec![
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        assert!(matching_target("abc", &targets).is_some());
+    }
+
+    #[test]
+    fn no_match_returns_none() {
+        let targets: HashSet<String> = vec!["deadbeef".to_string()].into_iter().collect();
+        assert!(matching_target("abc", &targets).is_none());
+    }
+}
@@ -16,24 +16,92 @@
 //! For each anagram use at most this many candidate words.
 //! Default 5 words.
 //! Use value 1 for single-word anagrams.
+//!
+//! -H hexdigest
+//! Repeatable. Instead of printing the top-scored anagrams, search for
+//! anagram phrases whose MD5 or SHA-256 digest equals the given hex digest.
+//!
+//! -t anagram_type
+//! One of `standard`, `proper`, `loose`. Default `standard`.
+//! `standard` folds case but keeps spacing/punctuation significant, as today.
+//! `loose` strips whitespace and punctuation and case-folds before matching,
+//! so multi-word phrases match regardless of spacing.
+//! `proper` does the same normalization as `loose`, but additionally
+//! suppresses solutions that are just the goal's own words reshuffled.
+//!
+//! -k slack
+//! Allow near-anagrams: up to this many leftover (missing) or overshot
+//! (surplus) letters. Default 0, which requires an exact anagram as today.
+//! Looser solutions (more slack used) sort after tighter ones.
 
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
+use std::str::FromStr;
 use structopt::StructOpt;
 
-use rayon::prelude::*;
-
 pub mod charcount;
 pub mod charlist;
 pub mod acompare;
+pub mod hashmatch;
 
 use charlist::CharList;
 use charlist::MatchResult;
+use charlist::SlackMatchResult;
+
+/// how aggressively input is normalized before being turned into a CharList,
+/// and whether trivial (goal-reshuffled) solutions are filtered out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AnagramType {
+    /// today's behavior: only case is folded, spacing/punctuation count.
+    Standard,
+    /// like Loose, but solutions that are just the goal's own words
+    /// reshuffled (including the goal itself) are suppressed.
+    Proper,
+    /// whitespace and punctuation are stripped and case is folded before
+    /// matching, so multi-word phrases match regardless of spacing.
+    Loose,
+}
+
+impl FromStr for AnagramType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "standard" => Ok(AnagramType::Standard),
+            "proper" => Ok(AnagramType::Proper),
+            "loose" => Ok(AnagramType::Loose),
+            other => Err(format!(
+                "unknown anagram type '{}', expected standard, proper or loose",
+                other
+            )),
+        }
+    }
+}
+
+/// normalize a string the way `mode` requires before it becomes a CharList.
+fn normalize_for_mode(mode: AnagramType, s: &str) -> String {
+    match mode {
+        AnagramType::Standard => s.to_lowercase(),
+        AnagramType::Proper | AnagramType::Loose => s
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect(),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// search for and score anagrams of a goal phrase
+    Search(SearchOpt),
+    /// list groups of mutually-anagrammatic dictionary words, largest first
+    Groups(GroupsOpt),
+}
 
 #[derive(Debug, StructOpt)]
-struct Opt {
+struct SearchOpt {
     /// the goal word to be anagrammatized
     #[structopt(short)]
     goal: String,
@@ -49,10 +117,32 @@ struct Opt {
     /// maximum count of anagrams to print
     #[structopt(short = "c", default_value = "10")]
     maximum_anagrams: i32,
+    /// hex digest (MD5 or SHA-256) to search for instead of scoring anagrams; repeatable
+    #[structopt(short = "H")]
+    hash: Vec<String>,
+    /// anagram matching mode: standard, proper, or loose
+    #[structopt(short = "t", default_value = "standard")]
+    anagram_type: AnagramType,
+    /// allow up to this many leftover/missing letters (near-anagrams); 0 (default) requires an exact match
+    #[structopt(short = "k", default_value = "0")]
+    slack: usize,
+}
+
+#[derive(Debug, StructOpt)]
+struct GroupsOpt {
+    /// The path to the file where words are
+    #[structopt(short, parse(from_os_str))]
+    wordfile: std::path::PathBuf,
+    /// minimum length of a candidate word
+    #[structopt(short = "m", default_value = "4")]
+    minimum_candidate: usize,
+    /// only print groups with at least this many mutually-anagrammatic members
+    #[structopt(short = "n", default_value = "2")]
+    minimum_group_size: usize,
 }
 
 /// # Input arguments
-/// 
+///
 /// -g goalword
 /// The word whose anagrams are searched for.
 ///
@@ -68,12 +158,37 @@ struct Opt {
 /// Default 5 words.
 /// Use value 1 for single-word anagrams.
 pub fn main() {
-    let opt = Opt::from_args();
+    match Command::from_args() {
+        Command::Search(opt) => run_search(opt),
+        Command::Groups(opt) => run_groups(opt),
+    }
+}
 
+/// list the dictionary's anagram groups (words sharing a CharList), largest
+/// first, skipping the goal-driven anagram/scoring machinery entirely.
+fn run_groups(opt: GroupsOpt) {
     println!("Reading candidate words...");
-    
-    let words = read_words(opt.wordfile, opt.minimum_candidate);
-    let goal = CharList::from_string(&opt.goal.to_lowercase());
+
+    let minimum_group_size = opt.minimum_group_size;
+    let words = read_words(opt.wordfile, opt.minimum_candidate, AnagramType::Standard);
+
+    let mut groups: Vec<&Vec<String>> = words
+        .values()
+        .filter(|group| group.len() >= minimum_group_size)
+        .collect();
+    groups.sort_by(|a, b| b.len().cmp(&a.len()));
+
+    for group in groups {
+        println!("{}", group.join(", "));
+    }
+}
+
+fn run_search(opt: SearchOpt) {
+    println!("Reading candidate words...");
+
+    let anagram_type = opt.anagram_type;
+    let goal = CharList::from_string(&normalize_for_mode(anagram_type, &opt.goal));
+    let words = read_words(opt.wordfile, opt.minimum_candidate, anagram_type);
     let mut candidates: Vec<&CharList> = Vec::new();
     for key in words.keys() {
         candidates.push(key)
@@ -81,45 +196,88 @@ pub fn main() {
     
     println!("Creating anagrams...");
 
-    let candidates = filter_and_sort_candidates(&goal, &candidates[..]);
-    let anagrams = anagram(&goal, candidates, opt.maximum_words_in_anagram);
+    if !opt.hash.is_empty() {
+        let candidates = filter_and_sort_candidates(&goal, &candidates[..]);
+        // stream solutions lazily so we can stop as soon as every target
+        // hash is found, instead of collecting the whole solution space.
+        let solutions = anagrams(goal.clone(), candidates, opt.maximum_words_in_anagram);
+        find_hash_targets(solutions, &words, &opt.hash);
+        return;
+    }
+
+    let solutions: Vec<(Vec<&CharList>, usize)> = if opt.slack > 0 {
+        // the exact pre-filter in filter_and_sort_candidates would drop
+        // every candidate that overshoots the goal, defeating the point of
+        // slack matching, so use the slack-aware bound instead.
+        let mut candidates = filter_candidates_with_slack(&goal, &candidates[..], opt.slack);
+        candidates.sort_by(|c1, c2| c2.length().cmp(&c1.length()));
+        anagram_with_slack(&goal, candidates, opt.maximum_words_in_anagram, opt.slack)
+    } else {
+        let candidates = filter_and_sort_candidates(&goal, &candidates[..]);
+        anagram(&goal, candidates, opt.maximum_words_in_anagram)
+            .into_iter()
+            .map(|solution| (solution, 0))
+            .collect()
+    };
 
     println!("Sorting anagrams...");
 
+    let mut goal_tokens: Vec<String> = opt
+        .goal
+        .split_whitespace()
+        .map(|w| normalize_for_mode(anagram_type, w))
+        .collect();
+    goal_tokens.sort();
+
     let mut all_anagrams = Vec::new();
-    for a in anagrams {
+    for (a, slack_used) in solutions {
+        if a.is_empty() {
+            // the whole goal was written off against the slack budget
+            // with no words at all -- not a real anagram.
+            continue;
+        }
         let strings = turn_into_strings(&a, &words);
         for s in strings.unwrap() {
+            if is_trivial_rearrangement(anagram_type, &goal_tokens, &s) {
+                continue;
+            }
             /*
             println!("{}", &s);
             */
-            all_anagrams.push(s);
+            all_anagrams.push((s, slack_used));
         }
     }
     let goalstring = opt.goal.to_string();
 
     struct AWithCount {
         count: usize,
-        string: String
+        string: String,
+        slack: usize,
     }
     let mut sorted_anagrams = Vec::new();
 
-    for string in all_anagrams {
+    for (string, slack) in all_anagrams {
         let ts = acompare::get_transpositions(goalstring.clone(), string.clone());
         let pts = ts.iter().map(|t| t).collect::<Vec<_>>();
-        let count = acompare::greedy_score(&pts);        
-        sorted_anagrams.push(AWithCount{count, string});
+        let count = acompare::greedy_score(&pts);
+        sorted_anagrams.push(AWithCount{count, string, slack});
     }
 
+    // tighter (less slack) anagrams first; among equally tight ones, the
+    // existing transposition score still decides the order.
     sorted_anagrams.sort_by(|c1, c2| {
-        c2.count.cmp(&c1.count)
+        c1.slack.cmp(&c2.slack).then(c2.count.cmp(&c1.count))
     });
 
     println!();
 
     let mut count = opt.maximum_anagrams;
     for a in sorted_anagrams {
-        println!("{}", a.string);
+        if a.slack > 0 {
+            println!("{} (slack {})", a.string, a.slack);
+        } else {
+            println!("{}", a.string);
+        }
         count = count - 1;
         if count <= 0 {
             break;
@@ -128,6 +286,69 @@ pub fn main() {
 
 }
 
+/// search an anagram iterator for phrases matching one of `hashes` (hex
+/// MD5 or SHA-256 digests), printing each match as it is found. Hashing is
+/// order-sensitive, so every word-order permutation of a multiset solution
+/// is expanded and hashed before giving up on it. Stops as soon as every
+/// target hash has been matched.
+fn find_hash_targets<'a>(
+    anagrams: impl Iterator<Item = Vec<&'a CharList>>,
+    words: &HashMap<Box<CharList>, Vec<String>>,
+    hashes: &[String],
+) {
+    let mut remaining: HashSet<String> = hashes.iter().map(|h| h.to_lowercase()).collect();
+
+    'search: for a in anagrams {
+        for order in permute_word_order(&a) {
+            if let Some(strings) = turn_into_strings(&order, words) {
+                for s in strings {
+                    if let Some(matched) = hashmatch::matching_target(&s, &remaining) {
+                        println!("{} matches {}", s, matched);
+                        remaining.remove(&matched);
+                        if remaining.is_empty() {
+                            break 'search;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// expand every word-order permutation of a multiset anagram solution, since
+/// `turn_into_strings` only ever emits phrases in the set's existing order.
+fn permute_word_order<'a>(set: &[&'a CharList]) -> Vec<Vec<&'a CharList>> {
+    if set.len() <= 1 {
+        return vec![set.to_vec()];
+    }
+    let mut out = Vec::new();
+    for i in 0..set.len() {
+        let mut rest = set.to_vec();
+        let first = rest.remove(i);
+        for mut tail in permute_word_order(&rest) {
+            let mut perm = vec![first];
+            perm.append(&mut tail);
+            out.push(perm);
+        }
+    }
+    out
+}
+
+/// in `proper` mode, a solution whose words (normalized and sorted) are
+/// exactly the goal's own words is not a genuine anagram and should be
+/// suppressed. `standard` and `loose` never suppress anything.
+fn is_trivial_rearrangement(anagram_type: AnagramType, goal_tokens: &[String], candidate: &str) -> bool {
+    if anagram_type != AnagramType::Proper {
+        return false;
+    }
+    let mut candidate_tokens: Vec<String> = candidate
+        .split_whitespace()
+        .map(|w| normalize_for_mode(anagram_type, w))
+        .collect();
+    candidate_tokens.sort();
+    candidate_tokens == goal_tokens
+}
+
 fn turn_into_strings(set: &[&CharList], words: &HashMap<Box<CharList>, Vec<String>>) -> Option<Vec<String>> {
     let rests = set.split_first();
     if let Some((first, rest)) = rests {
@@ -149,55 +370,61 @@ fn turn_into_strings(set: &[&CharList], words: &HashMap<Box<CharList>, Vec<Strin
     return None;
 }
 
+/// eager, `Vec`-collecting wrapper around `anagrams`. Kept for callers that
+/// want the whole solution space at once (e.g. to sort by score).
 fn anagram<'a>(
     goal: &CharList,
     words: Vec<&'a CharList>,
     iteration_level: usize,
 ) -> Vec<Vec<&'a CharList>> {
-    let results: Vec<Vec<&CharList>> = Vec::new();
-    if iteration_level == 0 {
-        return results;
-    }
+    anagrams(goal.clone(), words, iteration_level).collect()
+}
 
-    let results = words
-        .par_iter()
-        .enumerate()
-        .map(|(index, _)| {
-            try_one_word(goal, &words[index..], iteration_level)
-        })
-        .flatten()
-        .collect::<Vec<_>>();
-    return results;
+/// Lazily generate anagram solutions, yielding each as soon as the recursive
+/// backtracking (`try_one_word`) finds it, instead of collecting the entire
+/// solution space up front. Callers can `.take()` results or short-circuit,
+/// e.g. the hash-target search stops as soon as all target hashes are found.
+///
+/// The previous top-level fan-out over candidate words used `rayon`'s
+/// `par_iter`, but that doesn't compose with laziness: work-stealing threads
+/// would need to run ahead of whatever the caller has already consumed, with
+/// nowhere to put finished-but-not-yet-wanted results except back into a
+/// buffer, which defeats the point. So this traversal, and `anagram` which
+/// now wraps it, are sequential; `rayon` is no longer used. Each recursive
+/// step takes the remainder `CharList` by value, so it doesn't borrow a
+/// local created earlier in the call stack.
+pub fn anagrams<'a>(
+    goal: CharList,
+    candidates: Vec<&'a CharList>,
+    max_words: usize,
+) -> Box<dyn Iterator<Item = Vec<&'a CharList>> + 'a> {
+    if max_words == 0 {
+        return Box::new(std::iter::empty());
+    }
+    Box::new(
+        (0..candidates.len())
+            .flat_map(move |index| try_one_word(goal.clone(), candidates[index..].to_vec(), max_words)),
+    )
 }
 
 fn try_one_word<'a>(
-    goal: &CharList,
-    candidates: &[&'a CharList],
-    iteration_level: usize,
-) -> Vec<Vec<&'a CharList>> {
-    let mut results: Vec<Vec<&CharList>> = Vec::new();
-    let m = CharList::subtract(goal, candidates[0]);
-
-    match m {
-        MatchResult::NoMatch => (),
-        MatchResult::FullMatch => {
-            // add to results
-            results.push(vec![candidates[0]]);
-        }
+    goal: CharList,
+    candidates: Vec<&'a CharList>,
+    max_words: usize,
+) -> Box<dyn Iterator<Item = Vec<&'a CharList>> + 'a> {
+    let word = candidates[0];
+    match CharList::subtract(&goal, word) {
+        MatchResult::NoMatch => Box::new(std::iter::empty()),
+        MatchResult::FullMatch => Box::new(std::iter::once(vec![word])),
         MatchResult::PartialMatch(remains) => {
-            let word = candidates[0];
-            let candidates = filter_candidates(goal, candidates);
-            let new_anagrams = anagram(&remains, candidates, iteration_level - 1);
-            for news in new_anagrams {
+            let filtered = filter_candidates(&goal, &candidates);
+            Box::new(anagrams(remains, filtered, max_words - 1).map(move |mut rest| {
                 let mut first = vec![word];
-                for x in news {
-                    first.push(x);
-                }
-                results.push(first);
-            }
+                first.append(&mut rest);
+                first
+            }))
         }
     }
-    return results;
 }
 
 fn filter_candidates<'a>(
@@ -207,11 +434,92 @@ fn filter_candidates<'a>(
     let x = candidates
         .iter()
         .cloned()
-        .filter(|&c| c.length() <= goal.length() && CharList::may_be_contained(goal, c))
+        .filter(|&c| c.length() <= goal.length() && CharList::filter(goal, c))
         .collect::<Vec<_>>();
     return x;
 }
 
+/// like `anagram`, but tolerant of up to `max_slack` total missing or
+/// surplus letters: a solution can stop with some of the goal still
+/// uncovered, or use a candidate that overshoots the goal, as long as the
+/// combined slack spent stays within budget. Each returned solution carries
+/// how much slack it used, so tighter (lower-slack) anagrams can be ranked
+/// first.
+fn anagram_with_slack<'a>(
+    goal: &CharList,
+    words: Vec<&'a CharList>,
+    iteration_level: usize,
+    max_slack: usize,
+) -> Vec<(Vec<&'a CharList>, usize)> {
+    let mut results = Vec::new();
+
+    // stopping here entirely is valid as long as what's left of the goal
+    // fits the remaining slack budget (those letters are simply missing).
+    if goal.length() <= max_slack {
+        results.push((Vec::new(), goal.length()));
+    }
+
+    if iteration_level == 0 {
+        return results;
+    }
+
+    for index in 0..words.len() {
+        results.extend(try_one_word_with_slack(
+            goal,
+            &words[index..],
+            iteration_level,
+            max_slack,
+        ));
+    }
+    results
+}
+
+fn try_one_word_with_slack<'a>(
+    goal: &CharList,
+    candidates: &[&'a CharList],
+    iteration_level: usize,
+    max_slack: usize,
+) -> Vec<(Vec<&'a CharList>, usize)> {
+    let word = candidates[0];
+    match CharList::subtract_with_slack(goal, word, max_slack) {
+        SlackMatchResult::NoMatch => Vec::new(),
+        SlackMatchResult::Match {
+            remainder,
+            slack_used,
+        } => {
+            if remainder.length() == 0 {
+                return vec![(vec![word], slack_used)];
+            }
+            let budget_left = max_slack - slack_used;
+            let filtered = filter_candidates_with_slack(&remainder, candidates, budget_left);
+            anagram_with_slack(&remainder, filtered, iteration_level - 1, budget_left)
+                .into_iter()
+                .map(|(mut rest, rest_slack)| {
+                    let mut first = vec![word];
+                    first.append(&mut rest);
+                    (first, slack_used + rest_slack)
+                })
+                .collect()
+        }
+    }
+}
+
+/// like `filter_candidates`, but since slack mode allows a candidate to
+/// overshoot the goal by up to `max_slack` letters, it can't reject on
+/// `filter` the way the exact path does; `subtract_with_slack`
+/// itself is what enforces the budget.
+fn filter_candidates_with_slack<'a>(
+    goal: &CharList,
+    candidates: &[&'a CharList],
+    max_slack: usize,
+) -> Vec<&'a CharList> {
+    candidates
+        .iter()
+        .cloned()
+        .filter(|&c| c.length() <= goal.length() + max_slack)
+        .collect::<Vec<_>>()
+}
+
 fn filter_and_sort_candidates<'a>(
     goal: &CharList,
     candidates: &[&'a CharList],
@@ -219,7 +527,7 @@ fn filter_and_sort_candidates<'a>(
     let mut x = candidates
         .iter()
         .cloned()
-        .filter(|&c| c.length() <= goal.length() && CharList::may_be_contained(goal, c))
+        .filter(|&c| c.length() <= goal.length() && CharList::filter(goal, c))
         .collect::<Vec<_>>();
 
     // sort longest candidates to the front, this lessens the amount of backtracking
@@ -239,9 +547,12 @@ where
 // read_words reads a file of words, then builds a CharList of each.
 // it returns a HashMap where the CharList of each word is the key, and a vector of all words that have this CharList are the value.
 // This way, anagrammatic single words like 'karies', 'rieska' and 'eskari' occupy one slot in the HashMap.
+// CharList::from_string automatically picks the fast Dense lane representation for plain lowercase words
+// and falls back to Sparse for anything outside that bounded alphabet, so no extra wiring is needed here.
 fn read_words(
     filename: std::path::PathBuf,
     minimum_length: usize,
+    anagram_type: AnagramType,
 ) -> HashMap<Box<CharList>, Vec<String>> {
     let mut map = HashMap::new();
     match read_lines(filename) {
@@ -250,7 +561,7 @@ fn read_words(
                 match line {
                     Ok(word) => {
                         if word.len() >= minimum_length {
-                            let key = Box::new(CharList::from_string(&word.to_lowercase()));
+                            let key = Box::new(CharList::from_string(&normalize_for_mode(anagram_type, &word)));
                             let candidates = map.get_mut(&key);
                             match candidates {
                                 // Key does not exist. add it
@@ -274,3 +585,55 @@ fn read_words(
     };
     return map;
 }
+
+#[cfg(test)]
+mod tests {
+
+    use super::AnagramType;
+    use super::{is_trivial_rearrangement, normalize_for_mode};
+
+    #[test]
+    fn normalize_standard_only_folds_case() {
+        assert!(normalize_for_mode(AnagramType::Standard, "Cat, Dog!") == "cat, dog!");
+    }
+
+    #[test]
+    fn normalize_loose_strips_punctuation_and_case() {
+        assert!(normalize_for_mode(AnagramType::Loose, "Cat, Dog!") == "catdog");
+    }
+
+    #[test]
+    fn normalize_proper_strips_punctuation_and_case() {
+        assert!(normalize_for_mode(AnagramType::Proper, "Cat, Dog!") == "catdog");
+    }
+
+    #[test]
+    fn trivial_rearrangement_ignored_outside_proper_mode() {
+        let goal_tokens = vec!["cat".to_string(), "dog".to_string()];
+        assert!(!is_trivial_rearrangement(
+            AnagramType::Standard,
+            &goal_tokens,
+            "dog cat"
+        ));
+    }
+
+    #[test]
+    fn trivial_rearrangement_detects_reordered_goal_words() {
+        let goal_tokens = vec!["cat".to_string(), "dog".to_string()];
+        assert!(is_trivial_rearrangement(
+            AnagramType::Proper,
+            &goal_tokens,
+            "Dog, Cat!"
+        ));
+    }
+
+    #[test]
+    fn trivial_rearrangement_false_for_genuine_anagram() {
+        let goal_tokens = vec!["cat".to_string(), "dog".to_string()];
+        assert!(!is_trivial_rearrangement(
+            AnagramType::Proper,
+            &goal_tokens,
+            "cod gat"
+        ));
+    }
+}